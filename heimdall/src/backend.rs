@@ -0,0 +1,395 @@
+use std::{
+    error::Error,
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// The mechanism `Watcher` uses to load, detect changes in, and reload a plugin's
+/// state. `DyLibBackend` is the default implementation.
+pub trait AbstractPluginSource<State> {
+    /// The message type the host can push to the plugin through `Watcher::send`.
+    type Message;
+
+    /// Called once when the `Watcher` is constructed. Produces the initial state.
+    fn init(&mut self) -> Result<State, Box<dyn Error>>;
+
+    /// Returns `true` when the underlying source has changed since it was last loaded.
+    fn needs_reload(&mut self) -> Result<bool, Box<dyn Error>>;
+
+    /// Reloads the underlying source. Only called after `needs_reload` returns `true`.
+    fn reload(&mut self) -> Result<(), Box<dyn Error>>;
+
+    /// Returns `true` when a plugin is currently loaded and able to handle calls.
+    fn is_loaded(&self) -> bool;
+
+    /// Calls the `update` hook against the currently loaded source, if it exports one.
+    fn update(&self, state: &mut State);
+
+    /// Calls the `reload` hook against the currently loaded source, if it exports one.
+    fn on_reload(&self, state: &mut State);
+
+    /// Calls the `unload` hook against the currently loaded source, if it exports one.
+    fn on_unload(&self, state: &mut State);
+
+    /// Calls the `finalize` hook against the currently loaded source, if it exports one.
+    fn on_finalize(&self, state: &mut State);
+
+    /// Calls the `handle` hook against the currently loaded source, if it exports one.
+    fn handle(&self, state: &mut State, msg: Self::Message);
+}
+
+/// How `DyLibBackend` decides that the watched file has changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchMode {
+    /// A change is any mtime that advances past the one last loaded.
+    Mtime,
+    /// A change is a difference in a hash of the file's contents.
+    Hash,
+}
+
+/// A point-in-time fingerprint of the watched file, used to detect and debounce changes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Signature {
+    Mtime(SystemTime),
+    Hash(u64),
+}
+
+/// Tracks an in-flight change that hasn't been stable for `debounce` yet.
+struct PendingChange {
+    signature: Signature,
+    since: Instant,
+}
+
+/// The `heimdall_*` symbols resolved from a loaded library. Only `init` is required.
+struct Functions<State, Message> {
+    init: Option<unsafe fn() -> State>,
+    reload: Option<unsafe fn(&mut State)>,
+    unload: Option<unsafe fn(&mut State)>,
+    update: Option<unsafe fn(&mut State)>,
+    finalize: Option<unsafe fn(&mut State)>,
+    handle: Option<unsafe fn(&mut State, Message)>,
+}
+
+impl<State, Message> Functions<State, Message> {
+    /// Resolves every `heimdall_*` symbol, leaving missing ones as `None`.
+    fn resolve(lib: &libloading::Library) -> Self {
+        Self {
+            init: Self::symbol(lib, b"heimdall_init"),
+            reload: Self::symbol(lib, b"heimdall_reload"),
+            unload: Self::symbol(lib, b"heimdall_unload"),
+            update: Self::symbol(lib, b"heimdall_update"),
+            finalize: Self::symbol(lib, b"heimdall_finalize"),
+            handle: Self::symbol(lib, b"heimdall_handle"),
+        }
+    }
+
+    fn symbol<F: Copy>(lib: &libloading::Library, name: &[u8]) -> Option<F> {
+        // Copy the raw function pointer out of the `Symbol` so it can outlive the
+        // borrow of `lib`; it stays valid for as long as `lib` itself is kept loaded.
+        unsafe { lib.get::<F>(name) }.ok().map(|sym| *sym)
+    }
+}
+
+/// The original `Watcher` backend: a dynamic library on disk, cloned and reloaded
+/// whenever it is detected as changed.
+pub struct DyLibBackend<State, Message = ()> {
+    file_path: PathBuf,
+    last_loaded: Signature,
+    lib: Option<libloading::Library>,
+    functions: Option<Functions<State, Message>>,
+    /// How long the file must be unmodified after a detected change before it is
+    /// reloaded. Swallows the multiple writes a compiler emits while linking.
+    debounce: Duration,
+    /// Directory the cloned copy is loaded from, instead of next to `file_path`.
+    shadow_dir: Option<PathBuf>,
+    mode: WatchMode,
+    pending: Option<PendingChange>,
+}
+
+impl<State, Message> DyLibBackend<State, Message> {
+    /// Creates a new backend watching the dynamic library at `file_path`.
+    pub fn new(file_path: PathBuf) -> Self {
+        Self {
+            file_path,
+            last_loaded: Signature::Mtime(SystemTime::UNIX_EPOCH),
+            lib: None,
+            functions: None,
+            debounce: Duration::ZERO,
+            shadow_dir: None,
+            mode: WatchMode::Mtime,
+            pending: None,
+        }
+    }
+
+    /// Waits until the file has been stable for `debounce` before reloading it.
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    /// Loads the cloned copy from `shadow_dir` instead of alongside `file_path`.
+    pub fn with_shadow_dir(mut self, shadow_dir: PathBuf) -> Self {
+        self.shadow_dir = Some(shadow_dir);
+        self
+    }
+
+    /// Selects how a change in `file_path` is detected.
+    pub fn with_watch_mode(mut self, mode: WatchMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Computes the current signature of `file_path` according to `self.mode`.
+    fn signature(&self) -> Result<Signature, Box<dyn Error>> {
+        use std::fs::File;
+
+        match self.mode {
+            WatchMode::Mtime => {
+                let file = File::open(&self.file_path)?;
+                Ok(Signature::Mtime(file.metadata()?.modified()?))
+            }
+            WatchMode::Hash => Ok(Signature::Hash(Self::hash_file(&self.file_path)?)),
+        }
+    }
+
+    /// A cheap 64-bit hash of the file's contents.
+    fn hash_file(path: &PathBuf) -> Result<u64, Box<dyn Error>> {
+        use std::hash::{Hash, Hasher};
+
+        let bytes = std::fs::read(path)?;
+
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        bytes.hash(&mut hasher);
+
+        Ok(hasher.finish())
+    }
+
+    /// The resolved symbols for the currently loaded library, or `None` if unloaded.
+    fn functions(&self) -> Option<&Functions<State, Message>> {
+        self.functions.as_ref()
+    }
+
+    /// Clones the original lib, then loads and returns the clone alongside the
+    /// original's signature.
+    fn load_lib(
+        original_path: &PathBuf,
+        shadow_dir: &Option<PathBuf>,
+        mode: WatchMode,
+    ) -> Result<(libloading::Library, Signature), Box<dyn Error>> {
+        // Clone the DLL to enable watching of the original. While expensive, it bypasses
+        // a lot of issues that may occur when another process is modifying the original.
+        let cloned_name = Self::cloned_path(original_path, shadow_dir);
+        std::fs::copy(original_path, cloned_name.clone())?;
+
+        let signature = match mode {
+            WatchMode::Mtime => {
+                let file = std::fs::File::open(original_path)?;
+                Signature::Mtime(file.metadata()?.modified()?)
+            }
+            WatchMode::Hash => Signature::Hash(Self::hash_file(original_path)?),
+        };
+
+        // Load the lib
+        let lib = unsafe { libloading::Library::new(cloned_name.clone().as_os_str())? };
+
+        Ok((lib, signature))
+    }
+
+    /// Path the original is cloned to before loading: a file of the same name inside
+    /// `shadow_dir` if one is configured, otherwise a sibling `*_updated` file.
+    fn cloned_path(path: &PathBuf, shadow_dir: &Option<PathBuf>) -> PathBuf {
+        match shadow_dir {
+            Some(dir) => dir.join(path.file_name().unwrap()),
+            None => Self::make_cloned_name(path),
+        }
+    }
+
+    /// Creates the 'cloned' dll name
+    fn make_cloned_name(path: &PathBuf) -> PathBuf {
+        let file_name = path.file_name().unwrap();
+        let extension = path.extension().unwrap().to_str().unwrap();
+        let file_name = String::from(file_name.to_str().unwrap());
+
+        let mut file_name = file_name.replace(extension, "");
+        file_name.pop();
+        file_name.push_str("_updated");
+        file_name.push('.');
+        file_name.push_str(extension);
+
+        let mut path = path.clone();
+        path.set_file_name(file_name);
+
+        path
+    }
+}
+
+impl<State, Message> AbstractPluginSource<State> for DyLibBackend<State, Message> {
+    type Message = Message;
+
+    fn init(&mut self) -> Result<State, Box<dyn Error>> {
+        let (lib, signature) = Self::load_lib(&self.file_path, &self.shadow_dir, self.mode)?;
+        let functions = Functions::resolve(&lib);
+
+        let init_fn = functions.init.ok_or_else(|| -> Box<dyn Error> {
+            "plugin is missing required symbol `heimdall_init`".into()
+        })?;
+
+        let state = unsafe { init_fn() };
+
+        self.last_loaded = signature;
+        self.lib = Some(lib);
+        self.functions = Some(functions);
+
+        Ok(state)
+    }
+
+    fn needs_reload(&mut self) -> Result<bool, Box<dyn Error>> {
+        // `Mtime` mode only needs to re-check once the mtime itself advances, but `Hash`
+        // mode has to hash the file on every poll since content can change within the
+        // same mtime tick.
+        if self.mode == WatchMode::Mtime {
+            if let Signature::Mtime(last) = self.last_loaded {
+                let file = std::fs::File::open(&self.file_path)?;
+                if file.metadata()?.modified()? <= last {
+                    self.pending = None;
+                    return Ok(false);
+                }
+            }
+        }
+
+        let signature = self.signature()?;
+
+        if signature == self.last_loaded {
+            self.pending = None;
+            return Ok(false);
+        }
+
+        match &self.pending {
+            Some(pending) if pending.signature == signature => {
+                Ok(pending.since.elapsed() >= self.debounce)
+            }
+            _ => {
+                self.pending = Some(PendingChange {
+                    signature,
+                    since: Instant::now(),
+                });
+
+                Ok(false)
+            }
+        }
+    }
+
+    fn reload(&mut self) -> Result<(), Box<dyn Error>> {
+        self.lib = None;
+        self.functions = None;
+        self.pending = None;
+
+        let (lib, signature) = Self::load_lib(&self.file_path, &self.shadow_dir, self.mode)?;
+        let functions = Functions::resolve(&lib);
+
+        self.last_loaded = signature;
+        self.lib = Some(lib);
+        self.functions = Some(functions);
+
+        Ok(())
+    }
+
+    fn is_loaded(&self) -> bool {
+        self.lib.is_some()
+    }
+
+    fn update(&self, state: &mut State) {
+        if let Some(update) = self.functions().and_then(|f| f.update) {
+            unsafe { update(state) };
+        }
+    }
+
+    fn on_reload(&self, state: &mut State) {
+        if let Some(reload) = self.functions().and_then(|f| f.reload) {
+            unsafe { reload(state) };
+        }
+    }
+
+    fn on_unload(&self, state: &mut State) {
+        if let Some(unload) = self.functions().and_then(|f| f.unload) {
+            unsafe { unload(state) };
+        }
+    }
+
+    fn on_finalize(&self, state: &mut State) {
+        if let Some(finalize) = self.functions().and_then(|f| f.finalize) {
+            unsafe { finalize(state) };
+        }
+    }
+
+    fn handle(&self, state: &mut State, msg: Message) {
+        if let Some(handle) = self.functions().and_then(|f| f.handle) {
+            unsafe { handle(state, msg) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, thread, time::Duration as StdDuration};
+
+    fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("heimdall_backend_test_{name}"));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn mtime_mode_skips_rechecking_until_mtime_advances() {
+        let path = temp_file("mtime_short_circuit", b"v1");
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+
+        let mut backend = DyLibBackend::<(), ()>::new(path);
+        backend.last_loaded = Signature::Mtime(modified);
+
+        assert!(!backend.needs_reload().unwrap());
+    }
+
+    #[test]
+    fn debounce_delays_reload_until_change_is_stable() {
+        let path = temp_file("debounce", b"v1");
+
+        let mut backend =
+            DyLibBackend::<(), ()>::new(path).with_debounce(StdDuration::from_millis(30));
+
+        // First poll after the initial (unloaded) state just starts tracking the change.
+        assert!(!backend.needs_reload().unwrap());
+        // Still within the debounce window.
+        assert!(!backend.needs_reload().unwrap());
+
+        thread::sleep(StdDuration::from_millis(40));
+        assert!(backend.needs_reload().unwrap());
+    }
+
+    #[test]
+    fn hash_mode_detects_content_changes() {
+        let path = temp_file("hash_mode", b"v1");
+
+        let mut backend = DyLibBackend::<(), ()>::new(path.clone()).with_watch_mode(WatchMode::Hash);
+        backend.last_loaded = Signature::Hash(DyLibBackend::<(), ()>::hash_file(&path).unwrap());
+
+        fs::write(&path, b"v2").unwrap();
+
+        // First poll after the change just starts tracking it ...
+        assert!(!backend.needs_reload().unwrap());
+        // ... and is confirmed once it's stayed stable for the (zero) debounce window.
+        assert!(backend.needs_reload().unwrap());
+    }
+
+    #[test]
+    fn hash_mode_ignores_a_rewrite_with_identical_content() {
+        let path = temp_file("hash_mode_noop", b"v1");
+
+        let mut backend = DyLibBackend::<(), ()>::new(path.clone()).with_watch_mode(WatchMode::Hash);
+        backend.last_loaded = Signature::Hash(DyLibBackend::<(), ()>::hash_file(&path).unwrap());
+
+        fs::write(&path, b"v1").unwrap();
+        assert!(!backend.needs_reload().unwrap());
+    }
+}
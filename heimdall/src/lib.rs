@@ -1,4 +1,12 @@
-use std::{env, error::Error, marker::PhantomData, path::PathBuf};
+use std::{
+    cell::RefCell, collections::VecDeque, error::Error, marker::PhantomData, path::PathBuf,
+};
+
+mod backend;
+mod manager;
+
+pub use backend::{AbstractPluginSource, DyLibBackend, WatchMode};
+pub use manager::{PluginManager, PluginManagerConfig};
 
 /// Macro for enabling a watchable library.
 /// Ensure that
@@ -9,11 +17,29 @@ use std::{env, error::Error, marker::PhantomData, path::PathBuf};
 /// Is added to the crate.
 /// `state` is the state that will be utilized by the library. Typically this lives in the 'host' crate.
 /// `watchable` is an implementation of the `Watchable` trait. That ensures that the proper functionality is provided.
+/// `heimdall_init` is the only hook every plugin must export; the rest default to
+/// no-ops on `Watchable` so a plugin that doesn't implement them doesn't need to emit
+/// them either. Called with just `state`/`watchable`, emits every hook. Called with an
+/// explicit `hooks: [...]` list, emits only the ones named - use that for a `Watchable`
+/// impl that only overrides some of the optional hooks, to keep the compiled plugin
+/// free of symbols the host will never find a use for.
 #[macro_export]
 macro_rules! init_watchable {
     (
         state: $state:ty,
         watchable: $watchable:ty
+    ) => {
+        $crate::init_watchable!(
+            state: $state,
+            watchable: $watchable,
+            hooks: [reload, unload, update, finalize, handle]
+        );
+    };
+
+    (
+        state: $state:ty,
+        watchable: $watchable:ty,
+        hooks: [$($hook:ident),* $(,)?]
     ) => {
         use heimdall::Watchable;
 
@@ -23,44 +49,74 @@ macro_rules! init_watchable {
             <$watchable>::init()
         }
 
+        $(
+            $crate::init_watchable!(@hook $hook, $state, $watchable);
+        )*
+    };
+
+    (@hook reload, $state:ty, $watchable:ty) => {
         /// Watchable reload function
         #[no_mangle]
         pub extern "C" fn heimdall_reload(state: &mut $state) {
             <$watchable>::reload(state);
         }
+    };
 
+    (@hook unload, $state:ty, $watchable:ty) => {
         /// Watchable unload function
         #[no_mangle]
         pub extern "C" fn heimdall_unload(state: &mut $state) {
             <$watchable>::unload(state);
         }
+    };
 
+    (@hook update, $state:ty, $watchable:ty) => {
         /// Watchable update function
         #[no_mangle]
         pub extern "C" fn heimdall_update(state: &mut $state) {
             <$watchable>::update(state);
         }
+    };
 
+    (@hook finalize, $state:ty, $watchable:ty) => {
         /// Watchable finalize function
         #[no_mangle]
         pub extern "C" fn heimdall_finalize(state: &mut $state) {
             <$watchable>::finalize(state);
         }
     };
+
+    (@hook handle, $state:ty, $watchable:ty) => {
+        /// Watchable handle function
+        #[no_mangle]
+        pub extern "C" fn heimdall_handle(
+            state: &mut $state,
+            msg: <$watchable as Watchable<$state>>::Message,
+        ) {
+            <$watchable>::handle(state, msg);
+        }
+    };
 }
 
-/// Implementation required for a watchable library
+/// Implementation required for a watchable library. `init` is the only hook every
+/// plugin must provide; the rest default to no-ops so a plugin can override just the
+/// ones it needs.
 pub trait Watchable<State> {
+    /// The message type the host can push to the plugin through `Watcher::send`.
+    type Message;
+
     /// Called upon initial loading of the program
     fn init() -> State;
     /// Called when the module is reloaded
-    fn reload(state: &mut State);
+    fn reload(_state: &mut State) {}
     /// Called when the module is unloaded
-    fn unload(state: &mut State);
+    fn unload(_state: &mut State) {}
     /// Called when the program requires an update of the state
-    fn update(state: &mut State);
+    fn update(_state: &mut State) {}
     /// Called when the program is about to exit
-    fn finalize(state: &mut State);
+    fn finalize(_state: &mut State) {}
+    /// Called when the host sends `msg` to the plugin via `Watcher::send`
+    fn handle(_state: &mut State, _msg: Self::Message) {}
 }
 
 pub enum WatchResult {
@@ -69,104 +125,104 @@ pub enum WatchResult {
     Err(Box<dyn Error>),
 }
 
-pub struct Watcher<State, Plugin>
+/// Watches a plugin source and drives its lifecycle hooks. Generic over the `Backend`
+/// that actually loads and reloads the plugin; `DyLibBackend` is the default.
+pub struct Watcher<State, Plugin, Backend = DyLibBackend<State, <Plugin as Watchable<State>>::Message>>
 where
     Plugin: Watchable<State>,
+    Backend: AbstractPluginSource<State, Message = Plugin::Message>,
 {
     #[cfg(feature = "hot-reload")]
-    file_path: PathBuf,
+    backend: Backend,
+    /// Messages sent via `send` while no plugin is loaded, replayed once `watch`
+    /// finishes its next reload.
     #[cfg(feature = "hot-reload")]
-    last_updated: std::time::SystemTime,
+    queue: RefCell<VecDeque<Plugin::Message>>,
     #[cfg(feature = "hot-reload")]
-    lib: Option<libloading::Library>,
+    queue_capacity: Option<usize>,
 
-    phantom: PhantomData<(Plugin, State)>,
+    phantom: PhantomData<(Plugin, State, Backend)>,
 }
 
-impl<State, Plugin> Watcher<State, Plugin>
+impl<State, Plugin> Watcher<State, Plugin, DyLibBackend<State, Plugin::Message>>
 where
     Plugin: Watchable<State>,
 {
-    pub fn new(file_path: PathBuf) -> (Self, State) {
+    /// Creates a new Watcher backed by a `DyLibBackend` watching the dynamic library at
+    /// `file_path`.
+    pub fn new(file_path: PathBuf) -> Result<(Self, State), Box<dyn Error>> {
         #[cfg(not(feature = "hot-reload"))]
         {
             let state = Plugin::init();
 
-            (
+            Ok((
                 Self {
                     phantom: PhantomData,
                 },
                 state,
-            )
+            ))
         }
 
         #[cfg(feature = "hot-reload")]
         {
-            let (lib, last_updated) = Self::load_lib(&file_path).unwrap();
-            let state = Self::heimdall_init(&lib);
+            Self::with_backend(DyLibBackend::new(file_path))
+        }
+    }
+
+    /// Like `new`, but loads the cloned copy from `shadow_dir`.
+    pub fn with_shadow_dir(
+        file_path: PathBuf,
+        shadow_dir: PathBuf,
+    ) -> Result<(Self, State), Box<dyn Error>> {
+        #[cfg(not(feature = "hot-reload"))]
+        {
+            let state = Plugin::init();
 
-            (
+            Ok((
                 Self {
-                    file_path,
-                    last_updated,
-                    lib: Some(lib),
                     phantom: PhantomData,
                 },
                 state,
-            )
+            ))
         }
-    }
-
-    #[cfg(feature = "hot-reload")]
-    fn heimdall_init(lib: &libloading::Library) -> State {
-        let func: libloading::Symbol<unsafe fn() -> State> =
-            unsafe { lib.get(b"heimdall_init").unwrap() };
-        let state = unsafe { func() };
-
-        state
-    }
-
-    #[cfg(feature = "hot-reload")]
-    fn heimdall_update(lib: &libloading::Library, state: &mut State) {
-        let func: libloading::Symbol<unsafe fn(&mut State) -> State> =
-            unsafe { lib.get(b"heimdall_update").unwrap() };
-
-        unsafe {
-            func(state);
-        };
-    }
-
-    #[cfg(feature = "hot-reload")]
-    fn heimdall_unload(lib: &libloading::Library, state: &mut State) {
-        let func: libloading::Symbol<unsafe fn(&mut State) -> State> =
-            unsafe { lib.get(b"heimdall_unload").unwrap() };
 
-        unsafe {
-            func(state);
-        };
+        #[cfg(feature = "hot-reload")]
+        {
+            Self::with_backend(DyLibBackend::new(file_path).with_shadow_dir(shadow_dir))
+        }
     }
+}
 
+impl<State, Plugin, Backend> Watcher<State, Plugin, Backend>
+where
+    Plugin: Watchable<State>,
+    Backend: AbstractPluginSource<State, Message = Plugin::Message>,
+{
+    /// Creates a new Watcher driven by a custom `AbstractPluginSource` backend.
     #[cfg(feature = "hot-reload")]
-    fn heimdall_reload(lib: &libloading::Library, state: &mut State) {
-        let func: libloading::Symbol<unsafe fn(&mut State) -> State> =
-            unsafe { lib.get(b"heimdall_reload").unwrap() };
-
-        unsafe {
-            func(state);
-        };
+    pub fn with_backend(mut backend: Backend) -> Result<(Self, State), Box<dyn Error>> {
+        let state = backend.init()?;
+
+        Ok((
+            Self {
+                backend,
+                queue: RefCell::new(VecDeque::new()),
+                queue_capacity: None,
+                phantom: PhantomData,
+            },
+            state,
+        ))
     }
 
+    /// Bounds the number of messages buffered by `send` while unloaded, dropping the
+    /// oldest once exceeded. Unbounded by default.
     #[cfg(feature = "hot-reload")]
-    fn heimdall_finalize(lib: &libloading::Library, state: &mut State) {
-        let func: libloading::Symbol<unsafe fn(&mut State) -> State> =
-            unsafe { lib.get(b"heimdall_finalize").unwrap() };
-
-        unsafe {
-            func(state);
-        };
+    pub fn with_queue_capacity(mut self, capacity: usize) -> Self {
+        self.queue_capacity = Some(capacity);
+        self
     }
 
-    /// Watches the file
+    /// Watches the backend, reloading the plugin if it has changed.
     pub fn watch(&mut self, state: &mut State) -> WatchResult {
         #[cfg(not(feature = "hot-reload"))]
         {
@@ -175,38 +231,25 @@ where
 
         #[cfg(feature = "hot-reload")]
         {
-            use std::fs::File;
-
-            let file = match File::open(self.file_path.clone()) {
-                Ok(f) => f,
-                Err(e) => {
-                    return WatchResult::Err(Box::new(e));
-                }
-            };
+            match self.backend.needs_reload() {
+                Ok(true) => {
+                    self.backend.on_unload(state);
 
-            let last_updated = file.metadata().unwrap().modified().unwrap();
-
-            if last_updated > self.last_updated {
-                // Do unload
-                Self::heimdall_unload(self.lib(), state);
-
-                self.lib = None;
-
-                let (lib, last_updated) = match Self::load_lib(&self.file_path) {
-                    Ok(result) => result,
-                    Err(e) => {
+                    if let Err(e) = self.backend.reload() {
                         return WatchResult::Err(e);
                     }
-                };
 
-                self.last_updated = last_updated;
-                self.lib = Some(lib);
+                    self.backend.on_reload(state);
 
-                Self::heimdall_reload(self.lib(), state);
+                    let mut queue = self.queue.borrow_mut();
+                    while let Some(msg) = queue.pop_front() {
+                        self.backend.handle(state, msg);
+                    }
 
-                WatchResult::Updated
-            } else {
-                WatchResult::NoChange
+                    WatchResult::Updated
+                }
+                Ok(false) => WatchResult::NoChange,
+                Err(e) => WatchResult::Err(e),
             }
         }
     }
@@ -220,56 +263,120 @@ where
 
         #[cfg(feature = "hot-reload")]
         {
-            Self::heimdall_update(self.lib(), state);
+            self.backend.update(state);
         }
     }
 
-    #[cfg(feature = "hot-reload")]
-    fn lib(&self) -> &libloading::Library {
-        match &self.lib {
-            Some(lib) => lib,
-            None => panic!("Dynamic plugin has not been loaded!"),
+    /// Sends `msg` to the loaded plugin via its `handle` hook. If no plugin is
+    /// currently loaded, the message is buffered and replayed in order once `watch`
+    /// finishes its next reload.
+    pub fn send(&self, state: &mut State, msg: Plugin::Message) {
+        #[cfg(not(feature = "hot-reload"))]
+        {
+            Plugin::handle(state, msg);
+        }
+
+        #[cfg(feature = "hot-reload")]
+        {
+            if self.backend.is_loaded() {
+                self.backend.handle(state, msg);
+                return;
+            }
+
+            let mut queue = self.queue.borrow_mut();
+
+            if let Some(capacity) = self.queue_capacity {
+                if capacity == 0 {
+                    return;
+                }
+
+                while queue.len() >= capacity {
+                    queue.pop_front();
+                }
+            }
+
+            queue.push_back(msg);
         }
     }
+}
 
-    /// Clones the original lib, then returns a handle to the clone.
-    #[cfg(feature = "hot-reload")]
-    fn load_lib(
-        original_path: &PathBuf,
-    ) -> Result<(libloading::Library, std::time::SystemTime), Box<dyn Error>> {
-        use std::fs::File;
+#[cfg(all(test, feature = "hot-reload"))]
+mod tests {
+    use super::*;
 
-        // Clone the DLL to enable watching
-        let cloned_name = Self::make_cloned_name(original_path);
-        std::fs::copy(original_path, cloned_name.clone())?;
+    struct TestPlugin;
 
-        // Get the last updated
-        let file = File::open(original_path)?;
+    impl Watchable<()> for TestPlugin {
+        type Message = u32;
 
-        let last_updated = file.metadata()?.modified()?;
+        fn init() {}
+    }
+
+    struct MockBackend {
+        loaded: bool,
+    }
+
+    impl AbstractPluginSource<()> for MockBackend {
+        type Message = u32;
+
+        fn init(&mut self) -> Result<(), Box<dyn Error>> {
+            Ok(())
+        }
+
+        fn needs_reload(&mut self) -> Result<bool, Box<dyn Error>> {
+            Ok(false)
+        }
+
+        fn reload(&mut self) -> Result<(), Box<dyn Error>> {
+            Ok(())
+        }
+
+        fn is_loaded(&self) -> bool {
+            self.loaded
+        }
 
-        // Load the lib
-        let lib = unsafe { libloading::Library::new(cloned_name.clone().as_os_str())? };
+        fn update(&self, _state: &mut ()) {}
+        fn on_reload(&self, _state: &mut ()) {}
+        fn on_unload(&self, _state: &mut ()) {}
+        fn on_finalize(&self, _state: &mut ()) {}
+        fn handle(&self, _state: &mut (), _msg: u32) {}
+    }
 
-        Ok((lib, last_updated))
+    fn unloaded_watcher() -> Watcher<(), TestPlugin, MockBackend> {
+        Watcher::with_backend(MockBackend { loaded: false })
+            .unwrap()
+            .0
     }
 
-    /// Creates the 'cloned' dll name
-    #[cfg(feature = "hot-reload")]
-    fn make_cloned_name(path: &PathBuf) -> PathBuf {
-        let file_name = path.file_name().unwrap();
-        let extension = path.extension().unwrap().to_str().unwrap();
-        let file_name = String::from(file_name.to_str().unwrap());
+    #[test]
+    fn zero_capacity_drops_messages_without_buffering() {
+        let watcher = unloaded_watcher().with_queue_capacity(0);
+
+        watcher.send(&mut (), 1);
+        watcher.send(&mut (), 2);
+
+        assert!(watcher.queue.borrow().is_empty());
+    }
 
-        let mut file_name = file_name.replace(extension, "");
-        file_name.pop();
-        file_name.push_str("_updated");
-        file_name.push('.');
-        file_name.push_str(extension);
+    #[test]
+    fn capacity_evicts_the_oldest_message() {
+        let watcher = unloaded_watcher().with_queue_capacity(2);
 
-        let mut path = path.clone();
-        path.set_file_name(file_name);
+        watcher.send(&mut (), 1);
+        watcher.send(&mut (), 2);
+        watcher.send(&mut (), 3);
+
+        assert_eq!(*watcher.queue.borrow(), VecDeque::from(vec![2, 3]));
+    }
+
+    #[test]
+    fn unbounded_by_default() {
+        let watcher = unloaded_watcher();
+
+        for msg in 0..100 {
+            watcher.send(&mut (), msg);
+        }
 
-        path
+        assert_eq!(watcher.queue.borrow().len(), 100);
     }
 }
@@ -0,0 +1,280 @@
+use std::{error::Error, fs, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{AbstractPluginSource, DyLibBackend, WatchResult, Watchable, Watcher};
+
+/// Configuration for a `PluginManager`'s `[plugins]` section: where to scan for
+/// plugins, which ones are allowed to load, and what order to activate them in.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PluginManagerConfig {
+    /// Directory scanned for plugin dynamic libraries.
+    pub path: PathBuf,
+    /// Plugin names (file stems) that may not be loaded. Ignored when `as_whitelist` is set.
+    #[serde(default)]
+    pub blacklist: Vec<String>,
+    /// Plugin names (file stems) that are the only ones allowed to load. Only consulted
+    /// when `as_whitelist` is set.
+    #[serde(default)]
+    pub whitelist: Vec<String>,
+    /// Switches filtering from blacklist (deny-list) to whitelist (allow-list) mode.
+    #[serde(default)]
+    pub as_whitelist: bool,
+    /// Activation order. Named plugins load in this order first; any other allowed
+    /// plugin found in `path` loads afterwards, in directory order.
+    #[serde(default)]
+    pub template: Vec<String>,
+    /// Directory each plugin's cloned-before-load copy is written to. Defaults to a
+    /// `.shadow` subdirectory of `path`.
+    #[serde(default)]
+    pub shadow_dir: Option<PathBuf>,
+}
+
+impl PluginManagerConfig {
+    fn is_active(&self, name: &str) -> bool {
+        if self.as_whitelist {
+            self.whitelist.iter().any(|allowed| allowed == name)
+        } else {
+            !self.blacklist.iter().any(|denied| denied == name)
+        }
+    }
+
+    fn shadow_dir(&self) -> PathBuf {
+        self.shadow_dir
+            .clone()
+            .unwrap_or_else(|| self.path.join(".shadow"))
+    }
+}
+
+struct PluginEntry<State, Plugin, Backend>
+where
+    Plugin: Watchable<State>,
+    Backend: AbstractPluginSource<State, Message = Plugin::Message>,
+{
+    name: String,
+    watcher: Watcher<State, Plugin, Backend>,
+    state: State,
+}
+
+/// Owns a directory's worth of `Watcher`s, each a plugin discovered and filtered per a
+/// `PluginManagerConfig`, and sweeps them together with a single `watch_all`/
+/// `update_all` instead of wiring up one `Watcher` per plugin by hand.
+pub struct PluginManager<
+    State,
+    Plugin,
+    Backend = DyLibBackend<State, <Plugin as Watchable<State>>::Message>,
+> where
+    Plugin: Watchable<State>,
+    Backend: AbstractPluginSource<State, Message = Plugin::Message>,
+{
+    plugins: Vec<PluginEntry<State, Plugin, Backend>>,
+}
+
+impl<State, Plugin> PluginManager<State, Plugin, DyLibBackend<State, Plugin::Message>>
+where
+    Plugin: Watchable<State>,
+{
+    /// Scans `config.path` for dynamic libraries, loading every one allowed by the
+    /// blacklist/whitelist, in `config.template` order followed by any others found. A
+    /// plugin that fails to load is logged and skipped rather than aborting the manager.
+    pub fn new(config: &PluginManagerConfig) -> Result<Self, Box<dyn Error>> {
+        let mut discovered = Vec::new();
+
+        for entry in fs::read_dir(&config.path)? {
+            let path = entry?.path();
+
+            if !path.is_file() {
+                continue;
+            }
+
+            if path.extension().and_then(|ext| ext.to_str()) != Some(std::env::consts::DLL_EXTENSION)
+            {
+                continue;
+            }
+
+            let name = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+
+            // Ignore stray clones left by a misconfigured `shadow_dir`.
+            if name.ends_with("_updated") {
+                continue;
+            }
+
+            if config.is_active(&name) {
+                discovered.push((name, path));
+            }
+        }
+
+        let ordered = order_by_template(discovered, &config.template);
+
+        let shadow_dir = config.shadow_dir();
+        fs::create_dir_all(&shadow_dir)?;
+
+        let mut plugins = Vec::with_capacity(ordered.len());
+
+        for (name, path) in ordered {
+            match Watcher::with_shadow_dir(path, shadow_dir.clone()) {
+                Ok((watcher, state)) => plugins.push(PluginEntry {
+                    name,
+                    watcher,
+                    state,
+                }),
+                Err(e) => eprintln!("heimdall: skipping plugin `{}`: {}", name, e),
+            }
+        }
+
+        Ok(Self { plugins })
+    }
+}
+
+/// Moves the entries named in `template` to the front, in that order, leaving the rest
+/// in their discovered order.
+fn order_by_template(
+    mut discovered: Vec<(String, PathBuf)>,
+    template: &[String],
+) -> Vec<(String, PathBuf)> {
+    let mut ordered = Vec::with_capacity(discovered.len());
+
+    for name in template {
+        if let Some(index) = discovered.iter().position(|(found, _)| found == name) {
+            ordered.push(discovered.remove(index));
+        }
+    }
+
+    ordered.extend(discovered);
+    ordered
+}
+
+impl<State, Plugin, Backend> PluginManager<State, Plugin, Backend>
+where
+    Plugin: Watchable<State>,
+    Backend: AbstractPluginSource<State, Message = Plugin::Message>,
+{
+    /// Watches every plugin in activation order, reloading any that changed.
+    pub fn watch_all(&mut self) -> Vec<(&str, WatchResult)> {
+        self.plugins
+            .iter_mut()
+            .map(|plugin| {
+                (
+                    plugin.name.as_str(),
+                    plugin.watcher.watch(&mut plugin.state),
+                )
+            })
+            .collect()
+    }
+
+    /// Calls `update` on every loaded plugin, in activation order.
+    pub fn update_all(&mut self) {
+        for plugin in &mut self.plugins {
+            plugin.watcher.update(&mut plugin.state);
+        }
+    }
+
+    /// Looks up a plugin's state by name.
+    pub fn state(&self, name: &str) -> Option<&State> {
+        self.plugins
+            .iter()
+            .find(|plugin| plugin.name == name)
+            .map(|plugin| &plugin.state)
+    }
+
+    /// Looks up a plugin's state by name, mutably.
+    pub fn state_mut(&mut self, name: &str) -> Option<&mut State> {
+        self.plugins
+            .iter_mut()
+            .find(|plugin| plugin.name == name)
+            .map(|plugin| &mut plugin.state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(path: &str) -> PluginManagerConfig {
+        PluginManagerConfig {
+            path: PathBuf::from(path),
+            blacklist: Vec::new(),
+            whitelist: Vec::new(),
+            as_whitelist: false,
+            template: Vec::new(),
+            shadow_dir: None,
+        }
+    }
+
+    #[test]
+    fn blacklist_denies_named_plugins_only() {
+        let mut cfg = config("plugins");
+        cfg.blacklist = vec!["bad".to_string()];
+
+        assert!(cfg.is_active("good"));
+        assert!(!cfg.is_active("bad"));
+    }
+
+    #[test]
+    fn whitelist_allows_named_plugins_only() {
+        let mut cfg = config("plugins");
+        cfg.as_whitelist = true;
+        cfg.whitelist = vec!["good".to_string()];
+
+        assert!(cfg.is_active("good"));
+        assert!(!cfg.is_active("bad"));
+    }
+
+    #[test]
+    fn shadow_dir_defaults_under_path() {
+        let cfg = config("plugins");
+        assert_eq!(cfg.shadow_dir(), PathBuf::from("plugins/.shadow"));
+    }
+
+    #[test]
+    fn shadow_dir_honors_override() {
+        let mut cfg = config("plugins");
+        cfg.shadow_dir = Some(PathBuf::from("elsewhere"));
+        assert_eq!(cfg.shadow_dir(), PathBuf::from("elsewhere"));
+    }
+
+    type Discovered = Vec<(String, PathBuf)>;
+
+    fn discovered(names: &[&str]) -> Discovered {
+        names
+            .iter()
+            .map(|name| (name.to_string(), PathBuf::from(format!("{name}.so"))))
+            .collect()
+    }
+
+    fn names(entries: &Discovered) -> Vec<&str> {
+        entries.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    #[test]
+    fn template_moves_named_entries_to_the_front() {
+        let found = discovered(&["c", "a", "b"]);
+        let template = vec!["b".to_string(), "a".to_string()];
+
+        let ordered = order_by_template(found, &template);
+
+        assert_eq!(names(&ordered), vec!["b", "a", "c"]);
+    }
+
+    #[test]
+    fn template_entries_missing_from_discovery_are_ignored() {
+        let found = discovered(&["a"]);
+        let template = vec!["missing".to_string(), "a".to_string()];
+
+        let ordered = order_by_template(found, &template);
+
+        assert_eq!(names(&ordered), vec!["a"]);
+    }
+
+    #[test]
+    fn empty_template_preserves_discovery_order() {
+        let found = discovered(&["a", "b", "c"]);
+
+        let ordered = order_by_template(found, &[]);
+
+        assert_eq!(names(&ordered), vec!["a", "b", "c"]);
+    }
+}
@@ -4,7 +4,16 @@ heimdall::init_watchable!(state: State, watchable: Plugin);
 
 pub struct Plugin;
 
+/// App-specific events the host can push to the plugin via `Watcher::send`.
+#[derive(Debug)]
+pub enum Message {
+    Reset,
+    Tick,
+}
+
 impl heimdall::Watchable<State> for Plugin {
+    type Message = Message;
+
     fn init() -> State {
         println!("A init has occurred.");
 
@@ -30,4 +39,13 @@ impl heimdall::Watchable<State> for Plugin {
         state.counter = 0;
         println!("A finalize has occurred. State: {:?}", state);
     }
+
+    fn handle(state: &mut State, msg: Message) {
+        match msg {
+            Message::Reset => state.counter = 0,
+            Message::Tick => state.counter += 1,
+        }
+
+        println!("A message was handled. State: {:?}", state);
+    }
 }